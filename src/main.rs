@@ -3,8 +3,14 @@ use std::process::Command;
 use std::path::{Path, PathBuf};
 
 fn main() {
+    if std::env::args().any(|arg| arg == "--check-fmt") {
+        check_examples_fmt();
+        return;
+    }
     println!("Building start...");
     //testing();
+    checking_examples();
+    linting_examples();
     rendering();
     println!("Building complete.");
 }
@@ -20,63 +26,513 @@ fn testing() {
 
 fn rendering() {
     print!("Rendering...");
-    let (olds, news) = before_rendering();
+    let _relocation = ChapterRelocation::apply();
     let mut render_proc = Command::new("mdbook").arg("build").spawn()
                                 .expect("Failed to start the rendering process");
     let ecode = render_proc.wait().expect("Failed to finish the rendering process");
     assert!(ecode.success());
-    post_rendering(olds, news);
     println!("Done.");
 }
 
-fn before_rendering() -> (Vec<PathBuf>, Vec<PathBuf>) {
+// Pulls every fenced ```rust block out of each chapter linked from
+// SUMMARY.md, compiles and runs it, and compares the captured output against
+// a sibling fixture file. Goes beyond `mdbook test` (which only checks that
+// examples compile) by asserting the examples produce the output the prose
+// claims they do.
+fn checking_examples() {
+    print!("Checking examples...");
+    let root = std::env::current_dir().expect("WTF current directory does not exist");
+    for chapter in summary_paths(&root) {
+        let mut chapter_path = root.to_path_buf();
+        chapter_path.push(&chapter);
+        let content = std::fs::read_to_string(&chapter_path)
+            .unwrap_or_else(|e| panic!("Failed to read {}: {}", chapter, e));
+        for block in extract_rust_blocks(&content) {
+            run_example(&root, &chapter, block.range.start, &block.code, block.should_panic);
+        }
+    }
+    println!("Done.");
+}
+
+// Recognizes a ```rust fence and, if found, returns its mdbook attributes
+// (the comma-separated words after `rust`, e.g. `rust,ignore`).
+fn rust_fence_attrs(line: &str) -> Option<Vec<&str>> {
+    let rest = line.trim_start().strip_prefix("```rust")?;
+    Some(rest.trim().trim_start_matches(',').split(',').map(str::trim).filter(|a| !a.is_empty()).collect())
+}
+
+// `ignore` and `compile_fail` blocks are deliberately incomplete or broken,
+// and `no_run` blocks are deliberately not meant to be executed (they loop,
+// block on I/O, or are otherwise unsafe to just run) — none of the three is
+// something either check below can treat as ordinary Rust.
+fn is_runnable(attrs: &[&str]) -> bool {
+    !attrs.iter().any(|a| matches!(*a, "ignore" | "compile_fail" | "no_run"))
+}
+
+// mdbook hides lines prefixed with `# ` from the rendered book while still
+// compiling them, treats a lone `#` as a hidden blank line, and unescapes a
+// `##` prefix (used to show a literal `#`) down to a single `#`. Replicate
+// that convention so a block extracted here compiles the same way mdbook
+// would compile it.
+fn strip_hidden_lines(code: &str) -> String {
+    code.lines()
+        .map(|line| {
+            if let Some(rest) = line.strip_prefix("# ") {
+                rest.to_owned()
+            } else if line == "#" {
+                String::new()
+            } else if let Some(rest) = line.strip_prefix("##") {
+                format!("#{}", rest)
+            } else {
+                line.to_owned()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// mdbook also allows a snippet with no `fn main` to stand for the body of
+// one. `body` is indented one level, matching how rustfmt would lay out the
+// same statements once they're actually inside a function.
+fn wrap_bodiless_snippet(body: &str) -> String {
+    let indented: Vec<String> = body
+        .lines()
+        .map(|line| if line.is_empty() { String::new() } else { format!("    {}", line) })
+        .collect();
+    format!("fn main() {{\n{}\n}}\n", indented.join("\n"))
+}
+
+fn normalize_mdbook_snippet(code: &str) -> String {
+    let unhidden = strip_hidden_lines(code);
+    if unhidden.contains("fn main") {
+        format!("{}\n", unhidden)
+    } else {
+        wrap_bodiless_snippet(&unhidden)
+    }
+}
+
+// A single extracted ```rust fence, along with the byte range it spans
+// within its chapter and whether it carries the `should_panic` attribute.
+struct CodeBlock {
+    code: String,
+    range: std::ops::Range<usize>,
+    should_panic: bool,
+}
+
+// Extracts every runnable ```rust ... ``` fenced code block in `content`, in
+// order of appearance. The block's range covers the lines between its fences;
+// its start keys scratch files and fixtures by position in the source, so any
+// edit to the chapter ahead of the block changes that key.
+fn extract_rust_blocks(content: &str) -> Vec<CodeBlock> {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let mut offsets = Vec::with_capacity(lines.len() + 1);
+    let mut acc = 0;
+    for line in &lines {
+        offsets.push(acc);
+        acc += line.len();
+    }
+    offsets.push(acc);
+
+    let mut blocks = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        if let Some(attrs) = rust_fence_attrs(lines[i]) {
+            let start = offsets[i + 1];
+            let mut block = String::new();
+            let mut j = i + 1;
+            while j < lines.len() && !lines[j].trim_start().starts_with("```") {
+                block.push_str(lines[j]);
+                j += 1;
+            }
+            if is_runnable(&attrs) {
+                blocks.push(CodeBlock {
+                    code: block,
+                    range: start..offsets[j],
+                    should_panic: attrs.iter().any(|a| *a == "should_panic"),
+                });
+            }
+            i = j;
+        }
+        i += 1;
+    }
+    blocks
+}
+
+// We invoke `rustc` directly on the extracted snippet, with no `--extern`
+// flags and no access to the book's own Cargo dependencies, so a block that
+// pulls in an external crate can never compile this way. Rather than wiring
+// up real dependency resolution, we skip blocks that look like they need one
+// and leave them to whatever example crate under the book actually exercises
+// them. Only `use` and `extern crate` lines are inspected, and any path not
+// rooted at `std`, `core`, `alloc`, `crate`, `self` or `super` counts as
+// external, including a bare import from a module the block itself defines.
+fn uses_external_crate(code: &str) -> bool {
+    for line in code.lines() {
+        let line = line.trim_start();
+        let path = line
+            .strip_prefix("use ")
+            .or_else(|| line.strip_prefix("extern crate "));
+        let Some(path) = path else { continue };
+        let root = path.split([':', ' ', ';', '{']).next().unwrap_or("").trim();
+        if !matches!(root, "" | "std" | "core" | "alloc" | "crate" | "self" | "super") {
+            return true;
+        }
+    }
+    false
+}
+
+// Compiles and runs a single extracted code block in a scratch crate under
+// the OS temp directory, then diffs its stdout and stderr against
+// `<chapter>.<label>.out` and `<chapter>.<label>.err`, for whichever of the
+// two fixtures exist alongside the chapter. `label` is the byte offset at
+// which the block's code starts in the chapter, so it changes whenever text
+// ahead of the block is edited. Blocks with no fixtures are still run, and
+// only their exit status is checked. Blocks pulling in an external crate are
+// skipped, since we
+// have no dependency resolution here; `should_panic` blocks are expected to
+// exit non-zero instead of cleanly.
+fn run_example(root: &Path, chapter: &str, label: usize, code: &str, should_panic: bool) {
+    if uses_external_crate(code) {
+        println!("{} block at byte {} uses an external crate, skipping", chapter, label);
+        return;
+    }
+
+    let mut crate_dir = std::env::temp_dir();
+    crate_dir.push(format!("patterns-example-{}-{}", chapter.replace('/', "_"), label));
+    std::fs::create_dir_all(&crate_dir).expect("Failed to create a scratch directory for an example");
+
+    let mut main_rs = crate_dir.clone();
+    main_rs.push("main.rs");
+    std::fs::write(&main_rs, normalize_mdbook_snippet(code)).expect("Failed to write out an example's source");
+
+    let binary = crate_dir.join("example");
+    let compile = Command::new("rustc")
+        .arg("--edition").arg("2021")
+        .arg(&main_rs)
+        .arg("-o")
+        .arg(&binary)
+        .output()
+        .expect("Failed to invoke rustc");
+    if !compile.status.success() {
+        panic!("{} block at byte {} failed to compile:\n{}", chapter, label, String::from_utf8_lossy(&compile.stderr));
+    }
+
+    let run = Command::new(&binary)
+        .output()
+        .unwrap_or_else(|e| panic!("Failed to run {} block at byte {}: {}", chapter, label, e));
+
+    if run.status.success() == should_panic {
+        panic!(
+            "{} block at byte {} was expected to {}, but did not:\n{}",
+            chapter,
+            label,
+            if should_panic { "panic" } else { "run successfully" },
+            String::from_utf8_lossy(&run.stderr)
+        );
+    }
+
+    compare_against_fixture(root, chapter, label, "out", &String::from_utf8_lossy(&run.stdout));
+    compare_against_fixture(root, chapter, label, "err", &String::from_utf8_lossy(&run.stderr));
+}
+
+// Diffs `actual` against `<chapter>.<label>.<extension>`, if that fixture
+// exists alongside the chapter; a missing fixture means that stream is
+// unconstrained for this block. Used for both captured stdout (`.out`) and
+// stderr (`.err`).
+fn compare_against_fixture(root: &Path, chapter: &str, label: usize, extension: &str, actual: &str) {
+    let mut fixture = root.to_path_buf();
+    fixture.push(format!("{}.{}.{}", chapter, label, extension));
+    let expected = match std::fs::read_to_string(&fixture) {
+        Ok(expected) => expected,
+        Err(_) => return,
+    };
+
+    if actual != expected {
+        print_diff(chapter, label, &expected, actual);
+        panic!("{} block at byte {} produced unexpected {}", chapter, label, extension);
+    }
+}
+
+// Prints a unified-style diff of `expected` vs `actual`, with a couple of
+// lines of surrounding context around each run of differing lines.
+fn print_diff(chapter: &str, label: usize, expected: &str, actual: &str) {
+    const CONTEXT: usize = 2;
+    println!("--- {} block at byte {} (expected)", chapter, label);
+    println!("+++ {} block at byte {} (actual)", chapter, label);
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    let (e, a) = (expected.len(), actual.len());
+    let mut i = 0;
+    while i < e.max(a) {
+        if expected.get(i) == actual.get(i) {
+            i += 1;
+            continue;
+        }
+        let end = (i..e.max(a)).find(|&k| expected.get(k) == actual.get(k)).unwrap_or(e.max(a));
+        expected[i.saturating_sub(CONTEXT).min(e)..i.min(e)].iter().for_each(|l| println!("  {}", l));
+        expected[i.min(e)..end.min(e)].iter().for_each(|l| println!("- {}", l));
+        actual[i.min(a)..end.min(a)].iter().for_each(|l| println!("+ {}", l));
+        expected[end.min(e)..(end + CONTEXT).min(e)].iter().for_each(|l| println!("  {}", l));
+        i = end;
+    }
+}
+
+// Checked with `--check-fmt`: gathers every ```rust block from the chapters
+// linked in SUMMARY.md that `is_runnable` accepts and rejects any whose
+// formatting would change under rustfmt, so the hundreds of inline examples
+// stay as consistent as the prose around them. `ignore`, `compile_fail` and
+// `no_run` blocks are not checked.
+fn check_examples_fmt() {
+    print!("Checking example formatting...");
     let root = std::env::current_dir().expect("WTF current directory does not exist");
-    let file_paths = {
-            fn file_paths(spath: &Path) -> Vec<String> {
-                use std::io::{Read, Write};
-                let scontent = {
-                let sfile = std::fs::File::open(spath)
-                    .expect("Failed to open SUMMARY.md");
-                let mut buf = String::with_capacity(sfile.metadata().unwrap().len() as usize);
-                (&sfile).read_to_string(&mut buf).expect("Failed to read from SUMMARY.md");
-                buf
-            };
-
-            let mut paths = Vec::new();
-            for line in scontent.lines() {
-                if let Some(index) = line.find("(./") {
-                    let (_, path) = line.split_at(index+3);
-                    paths.push(path[..path.len()-1].to_owned());
-                }
+    let mut offenders = 0;
+    for chapter in summary_paths(&root) {
+        let mut chapter_path = root.to_path_buf();
+        chapter_path.push(&chapter);
+        let content = std::fs::read_to_string(&chapter_path)
+            .unwrap_or_else(|e| panic!("Failed to read {}: {}", chapter, e));
+        for block in extract_rust_blocks(&content) {
+            if !check_block_fmt(&chapter, block.range, &block.code) {
+                offenders += 1;
             }
-            paths
         }
-        let mut summary_path = root.to_path_buf();
-        summary_path.push("src");
-        summary_path.push("SUMMARY.md");
-        file_paths(summary_path.as_path())
+    }
+    if offenders > 0 {
+        panic!("{} code block(s) are not rustfmt-clean", offenders);
+    }
+    println!("Done.");
+}
+
+// Runs rustfmt over a single extracted block, after normalizing it the same
+// way mdbook would compile it, and reports whether it is already clean. A
+// block rustfmt can't parse even after normalizing (e.g. a deliberately
+// partial snippet) is skipped rather than treated as a failure, since it was
+// never going to be syntactically complete Rust. On mismatch, prints the
+// chapter, the block's byte range, and a diff against the formatted version.
+fn check_block_fmt(chapter: &str, range: std::ops::Range<usize>, code: &str) -> bool {
+    let normalized = normalize_mdbook_snippet(code);
+
+    let mut tmp = std::env::temp_dir();
+    tmp.push(format!("patterns-fmt-{}-{}.rs", chapter.replace('/', "_"), range.start));
+    std::fs::write(&tmp, &normalized).expect("Failed to write out a code block for formatting check");
+
+    let output = Command::new("rustfmt")
+        .arg("--edition").arg("2021")
+        .arg("--emit").arg("stdout")
+        .arg(&tmp)
+        .output()
+        .expect("Failed to invoke rustfmt");
+    if !output.status.success() {
+        println!(
+            "{} block at byte {} could not be parsed by rustfmt, skipping:\n{}",
+            chapter, range.start, String::from_utf8_lossy(&output.stderr)
+        );
+        return true;
+    }
+
+    // `--emit stdout` prints a `<path>:\n\n` banner ahead of the formatted
+    // source; strip it so the comparison is apples-to-apples.
+    let raw = String::from_utf8_lossy(&output.stdout).into_owned();
+    let banner = format!("{}:\n\n", tmp.display());
+    let formatted = raw.strip_prefix(banner.as_str()).unwrap_or(&raw).to_owned();
+    if formatted == normalized {
+        return true;
+    }
+    println!("{} bytes {}..{} would be reformatted:", chapter, range.start, range.end);
+    print_diff(chapter, range.start, &normalized, &formatted);
+    false
+}
+
+// Finds every standalone example crate in the repo (any directory holding a
+// `Cargo.toml`) and runs `cargo clippy` on it, so examples shipped as full
+// crates are held to the same quality bar as the inline snippets rather than
+// being left to rot.
+fn linting_examples() {
+    print!("Linting example crates...");
+    let root = std::env::current_dir().expect("WTF current directory does not exist");
+    let mut manifests = Vec::new();
+    collect_files(&root, |_| false, |p| p.file_name().and_then(|n| n.to_str()) == Some("Cargo.toml"), &mut manifests);
+
+    let mut failures = 0;
+    for manifest in &manifests {
+        let crate_dir = manifest.parent().expect("Cargo.toml has no parent directory");
+        let clippy = Command::new("cargo")
+            .arg("clippy")
+            .arg("--all-targets")
+            .arg("--manifest-path").arg(manifest)
+            .arg("--")
+            .arg("-D").arg("warnings")
+            .output()
+            .expect("Failed to invoke cargo clippy");
+        if !clippy.status.success() {
+            println!("{} produced clippy warnings/errors:", crate_dir.display());
+            print!("{}", String::from_utf8_lossy(&clippy.stderr));
+            failures += 1;
+        }
+    }
+    if failures > 0 {
+        panic!("{} of {} example crate(s) failed clippy", failures, manifests.len());
+    }
+    println!("Done.");
+}
+
+// Parses `src/SUMMARY.md` for `(./...)` links and returns the linked paths,
+// relative to `root`, in the order they appear.
+fn summary_paths(root: &Path) -> Vec<String> {
+    use std::io::Read;
+    let mut summary_path = root.to_path_buf();
+    summary_path.push("src");
+    summary_path.push("SUMMARY.md");
+    let scontent = {
+        let sfile = std::fs::File::open(&summary_path)
+            .expect("Failed to open SUMMARY.md");
+        let mut buf = String::with_capacity(sfile.metadata().unwrap().len() as usize);
+        (&sfile).read_to_string(&mut buf).expect("Failed to read from SUMMARY.md");
+        buf
     };
-    let mut old_paths = Vec::new();
-    let mut new_paths = Vec::new();
-    for path in &file_paths {
-        let mut old = root.to_path_buf();
-        old.push(&path);
-        let mut new = root.to_path_buf();
-        new.push("src");
-        new.push(&path);
-        std::fs::create_dir_all(new.parent().unwrap())
-            .expect("Failed to move a file");
-        std::fs::rename(old.as_path(), new.as_path())
-            .expect("Failed to move a file");
-        old_paths.push(old);
-        new_paths.push(new);
-    }
-    (old_paths, new_paths)
-}
-fn post_rendering(olds: Vec<PathBuf>, news: Vec<PathBuf>) {
-    assert_eq!(olds.len(), news.len());
-    for i in 0..olds.len() {
-        std::fs::rename(news[i].as_path(), olds[i].as_path())
-            .expect("Failed to move back!");
-    }
-}
\ No newline at end of file
+
+    let mut paths = Vec::new();
+    for line in scontent.lines() {
+        if let Some(index) = line.find("(./") {
+            let (_, path) = line.split_at(index+3);
+            paths.push(path[..path.len()-1].to_owned());
+        }
+    }
+    paths
+}
+
+// `mdbook` expects chapters directly under `src/`, but the repo keeps them
+// alongside their pattern for browsability, so they have to be moved in
+// before a build and back out afterwards. Holding this as a guard, rather
+// than a pair of free functions, means the files are moved back to their
+// original location on success, on an early return, or on an `mdbook`
+// failure unwinding through `assert!` — the working tree can no longer be
+// left stranded mid-build.
+struct ChapterRelocation {
+    moved: Vec<(PathBuf, PathBuf)>,
+}
+
+impl ChapterRelocation {
+    fn apply() -> Self {
+        let root = std::env::current_dir().expect("WTF current directory does not exist");
+        let file_paths = summary_paths(&root);
+        verify_summary_bijection(&root, &file_paths);
+
+        let mut relocation = ChapterRelocation { moved: Vec::new() };
+        for path in &file_paths {
+            let mut old = root.to_path_buf();
+            old.push(path);
+            let mut new = root.to_path_buf();
+            new.push("src");
+            new.push(path);
+            std::fs::create_dir_all(new.parent().unwrap())
+                .expect("Failed to move a file");
+            std::fs::rename(old.as_path(), new.as_path())
+                .expect("Failed to move a file");
+            relocation.moved.push((old, new));
+        }
+        relocation
+    }
+}
+
+impl Drop for ChapterRelocation {
+    fn drop(&mut self) {
+        // Never panic here: this commonly runs while already unwinding from
+        // an `mdbook` failure, and a panic during unwinding aborts the
+        // process before the original failure is ever reported.
+        for (old, new) in self.moved.drain(..) {
+            if !new.is_file() {
+                eprintln!("Warning: expected {} to move {} back, but it is gone", new.display(), old.display());
+                continue;
+            }
+            if let Err(e) = std::fs::rename(new.as_path(), old.as_path()) {
+                eprintln!("Warning: failed to move {} back to {}: {}", new.display(), old.display(), e);
+            }
+        }
+    }
+}
+// Filenames that are conventionally repo-root/project metadata rather than
+// book chapters, and so are never expected to be linked from SUMMARY.md.
+const NON_CHAPTER_MARKDOWN: &[&str] = &[
+    "README.md",
+    "CONTRIBUTING.md",
+    "CHANGELOG.md",
+    "CODE_OF_CONDUCT.md",
+    "LICENSE.md",
+    "PULL_REQUEST_TEMPLATE.md",
+];
+
+// Checks that every chapter linked from SUMMARY.md exists exactly once on
+// disk, and that every markdown file on disk is linked exactly once from
+// SUMMARY.md. Catches chapters that were written but never wired up, links
+// left dangling after a file was renamed or deleted, and chapters
+// accidentally linked twice.
+fn verify_summary_bijection(root: &Path, file_paths: &[String]) {
+    let mut linked = std::collections::HashSet::new();
+    for path in file_paths {
+        if !linked.insert(path.clone()) {
+            panic!("SUMMARY.md links to {} more than once", path);
+        }
+        let mut full = root.to_path_buf();
+        full.push(path);
+        if !full.is_file() {
+            panic!("SUMMARY.md links to {}, but that file does not exist", path);
+        }
+    }
+
+    let mut on_disk = Vec::new();
+    // Standalone example crates may carry their own README or design notes,
+    // and `.github` holds issue/PR templates; none of those are book
+    // chapters, so don't descend into a crate root or `.github`.
+    collect_files(
+        root,
+        |d| d.join("Cargo.toml").is_file() || d.file_name().and_then(|n| n.to_str()) == Some(".github"),
+        |p| p.extension().and_then(|e| e.to_str()) == Some("md"),
+        &mut on_disk,
+    );
+    let mut summary_path = root.to_path_buf();
+    summary_path.push("src");
+    summary_path.push("SUMMARY.md");
+    for file in &on_disk {
+        if file == &summary_path {
+            continue;
+        }
+        if file.file_name().and_then(|n| n.to_str()).is_some_and(|name| NON_CHAPTER_MARKDOWN.contains(&name)) {
+            continue;
+        }
+        let relative = file.strip_prefix(root).expect("walked file outside of root");
+        let relative = relative.to_str().expect("non-utf8 path").replace('\\', "/");
+        if !linked.contains(&relative) {
+            panic!("{} exists on disk but is not linked from SUMMARY.md", relative);
+        }
+    }
+}
+
+// Recursively walks `dir`, pushing every file for which `matches` returns
+// `true` onto `out`. Skips `.git`, `target` and `book`, which hold tooling
+// state and rendered output rather than source, plus any directory for
+// which `skip_dir` returns `true`. Shared by every pass that needs to find
+// files of a certain kind scattered across the repo, such as orphaned
+// chapters or example crates.
+fn collect_files(
+    dir: &Path,
+    skip_dir: impl Fn(&Path) -> bool + Copy,
+    matches: impl Fn(&Path) -> bool + Copy,
+    out: &mut Vec<PathBuf>,
+) {
+    for entry in std::fs::read_dir(dir).expect("Failed to read directory") {
+        let path = entry.expect("Failed to read directory entry").path();
+        if path.is_dir() {
+            match path.file_name().and_then(|n| n.to_str()) {
+                Some(".git") | Some("target") | Some("book") => continue,
+                _ => {}
+            }
+            if skip_dir(&path) {
+                continue;
+            }
+            collect_files(&path, skip_dir, matches, out);
+        } else if matches(&path) {
+            out.push(path);
+        }
+    }
+}